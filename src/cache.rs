@@ -0,0 +1,283 @@
+use crate::status::{self, Backend, Counts};
+use git2::{Branch, Repository};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const CACHE_FILE_NAME: &str = "pretty-git-status-cache.json";
+
+/// Computes status the same as [`status::compute`], but reuses the last
+/// cached result when nothing that would change it has happened: HEAD,
+/// the upstream ref, and `refs/stash` are all unchanged, `.git/index`
+/// hasn't been touched, and no tracked file or tracked-file directory has
+/// a newer mtime than what was recorded (a directory's mtime changes when
+/// an entry is added or removed, which is how untracked files are caught
+/// even though they have no index entry of their own). This is the same
+/// index-mtime shortcut editor git integrations use to skip a full
+/// working-tree scan.
+pub fn compute_cached(repo: &Repository, backend: Backend) -> Counts {
+    if let Some(counts) = try_load(repo) {
+        return counts;
+    }
+
+    let counts = status::compute(repo, backend);
+
+    store(repo, &counts);
+
+    counts
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    head_oid: String,
+    upstream_oid: Option<String>,
+    stash_oid: Option<String>,
+    index_mtime: Timestamp,
+    max_relevant_mtime: Timestamp,
+    counts: CachedCounts,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+struct Timestamp {
+    secs: u64,
+    nanos: u32,
+}
+
+impl Timestamp {
+    fn from_system_time(time: SystemTime) -> Option<Timestamp> {
+        let duration = time.duration_since(UNIX_EPOCH).ok()?;
+
+        Some(Timestamp {
+            secs: duration.as_secs(),
+            nanos: duration.subsec_nanos(),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedCounts {
+    total_untracked: i32,
+    total_changed: i32,
+    total_staged: i32,
+    total_conflicted: i32,
+    total_stashed: i32,
+    is_local_only_branch: bool,
+    ahead: usize,
+    behind: usize,
+}
+
+impl From<&Counts> for CachedCounts {
+    fn from(counts: &Counts) -> Self {
+        CachedCounts {
+            total_untracked: counts.total_untracked,
+            total_changed: counts.total_changed,
+            total_staged: counts.total_staged,
+            total_conflicted: counts.total_conflicted,
+            total_stashed: counts.total_stashed,
+            is_local_only_branch: counts.is_local_only_branch,
+            ahead: counts.ahead,
+            behind: counts.behind,
+        }
+    }
+}
+
+impl From<CachedCounts> for Counts {
+    fn from(cached: CachedCounts) -> Self {
+        Counts {
+            total_untracked: cached.total_untracked,
+            total_changed: cached.total_changed,
+            total_staged: cached.total_staged,
+            total_conflicted: cached.total_conflicted,
+            total_stashed: cached.total_stashed,
+            is_local_only_branch: cached.is_local_only_branch,
+            ahead: cached.ahead,
+            behind: cached.behind,
+        }
+    }
+}
+
+fn try_load(repo: &Repository) -> Option<Counts> {
+    let contents = fs::read_to_string(cache_path(repo)?).ok()?;
+    let cached: CacheFile = serde_json::from_str(&contents).ok()?;
+
+    if cached.head_oid != current_head_oid(repo)? {
+        return None;
+    }
+
+    if cached.upstream_oid != upstream_oid(repo) {
+        return None;
+    }
+
+    if cached.stash_oid != stash_oid(repo) {
+        return None;
+    }
+
+    if cached.index_mtime != index_mtime(repo)? {
+        return None;
+    }
+
+    if any_relevant_path_newer_than(repo, cached.max_relevant_mtime)? {
+        return None;
+    }
+
+    Some(cached.counts.into())
+}
+
+fn store(repo: &Repository, counts: &Counts) -> Option<()> {
+    let cache = CacheFile {
+        head_oid: current_head_oid(repo)?,
+        upstream_oid: upstream_oid(repo),
+        stash_oid: stash_oid(repo),
+        index_mtime: index_mtime(repo)?,
+        max_relevant_mtime: max_relevant_mtime(repo)?,
+        counts: counts.into(),
+    };
+
+    let serialized = serde_json::to_string(&cache).ok()?;
+
+    fs::write(cache_path(repo)?, serialized).ok()
+}
+
+fn cache_path(repo: &Repository) -> Option<PathBuf> {
+    Some(repo.path().join(CACHE_FILE_NAME))
+}
+
+fn current_head_oid(repo: &Repository) -> Option<String> {
+    Some(repo.head().ok()?.target()?.to_string())
+}
+
+/// The current branch's upstream tip, if any. A completed `git fetch`
+/// (including the chunk0-2 background fetch) moves this even when
+/// nothing in the working tree or index changes, so it must gate the
+/// cached `ahead`/`behind` counts.
+fn upstream_oid(repo: &Repository) -> Option<String> {
+    let head = repo.head().ok()?;
+
+    if !head.is_branch() {
+        return None;
+    }
+
+    let upstream = Branch::wrap(head).upstream().ok()?;
+
+    Some(upstream.get().target()?.to_string())
+}
+
+/// The tip of `refs/stash`, if any. Pushing, popping, or dropping a stash
+/// entry moves this without touching the index or any tracked file.
+fn stash_oid(repo: &Repository) -> Option<String> {
+    repo.refname_to_id("refs/stash")
+        .ok()
+        .map(|oid| oid.to_string())
+}
+
+fn index_mtime(repo: &Repository) -> Option<Timestamp> {
+    let mut index_path = repo.path().to_owned();
+
+    index_path.push("index");
+
+    let metadata = fs::metadata(index_path).ok()?;
+
+    Timestamp::from_system_time(metadata.modified().ok()?)
+}
+
+/// Every tracked file (so edits are caught), plus every directory that
+/// holds one and the workdir root (so a sibling untracked file being
+/// created or removed is caught too, since that changes the containing
+/// directory's mtime even though the file itself has no index entry).
+/// New untracked files in a wholly-untracked directory won't move any of
+/// these mtimes; `index_mtime` staying put is the accepted tradeoff for
+/// not re-walking the whole tree.
+struct RelevantPaths {
+    files: BTreeSet<PathBuf>,
+    dirs: BTreeSet<PathBuf>,
+}
+
+fn relevant_paths(repo: &Repository) -> Option<RelevantPaths> {
+    let workdir = repo.workdir()?;
+    let index = repo.index().ok()?;
+
+    let mut files = BTreeSet::new();
+    let mut dirs = BTreeSet::new();
+
+    dirs.insert(workdir.to_owned());
+
+    for entry in index.iter() {
+        let path = workdir.join(String::from_utf8_lossy(&entry.path).as_ref());
+
+        let mut dir = path.parent();
+
+        // Every ancestor up to the workdir root, so a directory whose only
+        // tracked files sit in subdirectories is still watched. Stops early
+        // once an ancestor is already recorded (its own ancestors are too).
+        while let Some(parent) = dir {
+            if !parent.starts_with(workdir) || !dirs.insert(parent.to_owned()) {
+                break;
+            }
+
+            dir = parent.parent();
+        }
+
+        files.insert(path);
+    }
+
+    Some(RelevantPaths { files, dirs })
+}
+
+/// The newest mtime among `relevant_paths`, used so a later cache lookup
+/// can tell whether anything has been touched since.
+fn max_relevant_mtime(repo: &Repository) -> Option<Timestamp> {
+    let paths = relevant_paths(repo)?;
+    let mut newest = UNIX_EPOCH;
+
+    for path in paths.files.iter().chain(paths.dirs.iter()) {
+        if let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) {
+            if modified > newest {
+                newest = modified;
+            }
+        }
+    }
+
+    Timestamp::from_system_time(newest)
+}
+
+/// True if any relevant path now has a newer mtime than `since`, or a
+/// tracked file has disappeared entirely (both mean the cached counts are
+/// stale).
+fn any_relevant_path_newer_than(repo: &Repository, since: Timestamp) -> Option<bool> {
+    let paths = relevant_paths(repo)?;
+
+    for file in &paths.files {
+        match fs::metadata(file).and_then(|m| m.modified()) {
+            Ok(modified) => {
+                if Timestamp::from_system_time(modified)? > since {
+                    return Some(true);
+                }
+            }
+            Err(_) => return Some(true),
+        }
+    }
+
+    for dir in &paths.dirs {
+        if let Ok(modified) = fs::metadata(dir).and_then(|m| m.modified()) {
+            if Timestamp::from_system_time(modified)? > since {
+                return Some(true);
+            }
+        }
+    }
+
+    Some(false)
+}
+
+impl PartialOrd for Timestamp {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timestamp {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.secs, self.nanos).cmp(&(other.secs, other.nanos))
+    }
+}
@@ -0,0 +1,163 @@
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+/// Symbols used for each rendered segment. Defaults match the original
+/// hardcoded glyphs, except `ahead`/`behind`, which default to `⇡`/`⇣`
+/// (formerly `↑`/`↓`), and `diverged`, which has no original counterpart.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Symbols {
+    pub local_only: String,
+    pub ahead: String,
+    pub behind: String,
+    pub diverged: String,
+    pub untracked: String,
+    pub changed: String,
+    pub staged: String,
+    pub conflicted: String,
+    pub stash: String,
+}
+
+impl Default for Symbols {
+    fn default() -> Self {
+        Symbols {
+            local_only: String::from("⬨"),
+            ahead: String::from("⇡"),
+            behind: String::from("⇣"),
+            diverged: String::from("⇕"),
+            untracked: String::from("+"),
+            changed: String::from("Δ"),
+            staged: String::from("●"),
+            conflicted: String::from("✖"),
+            stash: String::from("⚑"),
+        }
+    }
+}
+
+/// Per-segment colors, expressed as truecolor hex (`"#rrggbb"`) or one of
+/// the named ANSI colors accepted by `colored_truecolor`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Colors {
+    pub branch: String,
+    pub untracked: String,
+    pub changed: String,
+    pub staged: String,
+    pub conflicted: String,
+}
+
+// `colored_truecolor::Color`'s `FromStr` only accepts these exact,
+// lowercase, space-separated spellings (e.g. "bright magenta", not
+// "bright_magenta" or "brightmagenta") — `main::paint` also normalizes
+// underscores to spaces before parsing so a config file using either
+// style still works, but the defaults here are kept in the form the
+// crate actually parses so they never silently fall back to unpainted
+// text.
+const DEFAULT_BRANCH_COLOR: &str = "magenta";
+const DEFAULT_UNTRACKED_COLOR: &str = "cyan";
+const DEFAULT_CHANGED_COLOR: &str = "bright magenta";
+const DEFAULT_STAGED_COLOR: &str = "red";
+const DEFAULT_CONFLICTED_COLOR: &str = "yellow";
+
+impl Default for Colors {
+    fn default() -> Self {
+        Colors {
+            branch: String::from(DEFAULT_BRANCH_COLOR),
+            untracked: String::from(DEFAULT_UNTRACKED_COLOR),
+            changed: String::from(DEFAULT_CHANGED_COLOR),
+            staged: String::from(DEFAULT_STAGED_COLOR),
+            conflicted: String::from(DEFAULT_CONFLICTED_COLOR),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Controls segment order and which segments appear at all.
+    /// Recognized tokens: `$branch`, `$sync`, `$counts`, `$stash`.
+    pub format: String,
+
+    /// When false, ahead/behind/diverged counts are never rendered.
+    pub show_sync_count: bool,
+
+    /// When false, the local-only branch marker (`symbols.local_only`) is
+    /// never rendered, e.g. for throwaway branches with no upstream. A
+    /// detached HEAD never shows this marker either way.
+    pub show_local_only_marker: bool,
+
+    /// When false, a diverged branch renders just `symbols.diverged` with
+    /// no ahead/behind counts after it.
+    pub show_diverged_counts: bool,
+
+    /// Printed immediately before the status group.
+    pub prefix: String,
+
+    /// Printed immediately after the status group.
+    pub suffix: String,
+
+    pub symbols: Symbols,
+    pub colors: Colors,
+
+    /// Which backend computes working-tree status: `"auto"`, `"git"`, or
+    /// `"libgit2"`. See [`crate::status::Backend`].
+    pub status_backend: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            format: String::from("$branch$sync$counts$stash"),
+            show_sync_count: true,
+            show_local_only_marker: true,
+            show_diverged_counts: true,
+            prefix: String::new(),
+            suffix: String::new(),
+            symbols: Symbols::default(),
+            colors: Colors::default(),
+            status_backend: String::from("auto"),
+        }
+    }
+}
+
+impl Config {
+    /// Loads config from `PRETTY_GIT_STATUS_CONFIG` if set, otherwise from
+    /// `$XDG_CONFIG_HOME/pretty-git-status/config.toml`, or
+    /// `~/.config/pretty-git-status/config.toml` when `XDG_CONFIG_HOME` is
+    /// unset. Falls back to defaults
+    /// when no file is found or it fails to parse.
+    pub fn load() -> Config {
+        let path = match env::var_os("PRETTY_GIT_STATUS_CONFIG") {
+            Some(path) => Some(PathBuf::from(path)),
+            None => default_config_path(),
+        };
+
+        let path = match path {
+            Some(path) => path,
+            None => return Config::default(),
+        };
+
+        match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents).unwrap_or_default(),
+            Err(_) => Config::default(),
+        }
+    }
+}
+
+fn default_config_path() -> Option<PathBuf> {
+    let mut path = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let mut home = PathBuf::from(env::var_os("HOME")?);
+            home.push(".config");
+            home
+        }
+    };
+
+    path.push("pretty-git-status");
+    path.push("config.toml");
+
+    Some(path)
+}
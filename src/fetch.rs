@@ -0,0 +1,256 @@
+use git2::{Cred, CredentialType, FetchOptions, RemoteCallbacks, Repository};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+pub const BACKGROUND_FETCH_FLAG: &str = "--background-fetch";
+
+const THROTTLE: Duration = Duration::from_secs(60 * 15);
+
+const LOCK_FILE_NAME: &str = "pretty-git-status-fetch.lock";
+
+// Comfortably longer than any fetch should take against a reachable
+// remote, but far short of `THROTTLE`, so a lock left behind by a child
+// that got killed before it could clean up doesn't wedge fetching forever.
+const LOCK_STALE_AFTER: Duration = Duration::from_secs(120);
+
+/// If we haven't fetched in `THROTTLE` and no background fetch is already
+/// running, spawns a detached copy of this binary to do the fetch so
+/// `main()` never blocks on the network. The child is fully disowned: no
+/// stdio inherited, and we don't wait on it.
+///
+/// libgit2 only writes `FETCH_HEAD` once a fetch *completes*, so for the
+/// slow/unreachable remotes this is meant to help with, `fetch_is_due`
+/// alone would stay true for the whole network-timeout window and we'd
+/// spawn a new fetch on every prompt. A lock file, held for the lifetime
+/// of the child, closes that gap.
+pub fn spawn_background_fetch_if_due(repo: &Repository) -> Option<()> {
+    let head = repo.head().ok()?;
+
+    // If we're not on a branch, don't bother
+    if !head.is_branch() {
+        return None;
+    }
+
+    if !fetch_is_due(repo) {
+        return None;
+    }
+
+    if fetch_is_in_progress(repo) {
+        return None;
+    }
+
+    acquire_lock(repo)?;
+
+    let exe = env::current_exe().ok()?;
+    let current_dir = env::current_dir().ok()?;
+
+    let spawned = Command::new(exe)
+        .arg(BACKGROUND_FETCH_FLAG)
+        .current_dir(current_dir)
+        .env(
+            crate::log::SESSION_ID_VAR,
+            crate::log::shell_session_id().to_string(),
+        )
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+
+    if spawned.is_err() {
+        release_lock(repo);
+        return None;
+    }
+
+    Some(())
+}
+
+/// True when we have no `FETCH_HEAD`, or it's older than `THROTTLE`.
+fn fetch_is_due(repo: &Repository) -> bool {
+    let mut fetch_head_path = repo.path().to_owned();
+
+    fetch_head_path.push("FETCH_HEAD");
+
+    match fs::metadata(fetch_head_path).and_then(|m| m.modified()) {
+        Ok(modified) => modified.elapsed().map(|e| e >= THROTTLE).unwrap_or(true),
+        Err(_) => true,
+    }
+}
+
+fn lock_path(repo: &Repository) -> std::path::PathBuf {
+    repo.path().join(LOCK_FILE_NAME)
+}
+
+/// True when a lock file exists and is recent enough to still be a live
+/// background fetch rather than debris from one that never cleaned up.
+fn fetch_is_in_progress(repo: &Repository) -> bool {
+    match fs::metadata(lock_path(repo)).and_then(|m| m.modified()) {
+        Ok(modified) => modified.elapsed().map(|e| e < LOCK_STALE_AFTER).unwrap_or(true),
+        Err(_) => false,
+    }
+}
+
+fn acquire_lock(repo: &Repository) -> Option<()> {
+    fs::write(lock_path(repo), b"").ok()
+}
+
+fn release_lock(repo: &Repository) {
+    let _ = fs::remove_file(lock_path(repo));
+}
+
+/// Entry point for the detached background-fetch process: opens the repo
+/// fresh, fetches the current branch's upstream, and lets libgit2 update
+/// `FETCH_HEAD` as a side effect. Called from `main()` when invoked with
+/// `--background-fetch`. Failures are swallowed as far as the prompt is
+/// concerned, but logged via `log::log_fetch_event` when diagnostics are
+/// enabled. Once the repository is open, releases the in-progress lock
+/// whether or not the fetch succeeds, so the next due fetch isn't blocked
+/// by this one; if opening fails, the lock stays until it is older than
+/// `LOCK_STALE_AFTER`.
+pub fn run_background_fetch() {
+    let repo = match Repository::open_from_env() {
+        Ok(repo) => repo,
+        Err(e) => {
+            crate::log::log_fetch_event(&format!("opening repository: {}", e));
+            return;
+        }
+    };
+
+    let result = fetch_current_branch(&repo);
+
+    release_lock(&repo);
+
+    if let Err(message) = result {
+        crate::log::log_fetch_event(&message);
+    }
+}
+
+fn fetch_current_branch(repo: &Repository) -> Result<(), String> {
+    let head = repo
+        .head()
+        .map_err(|e| format!("reading HEAD: {}", e))?;
+
+    if !head.is_branch() {
+        return Err(String::from("HEAD is not a branch, skipping fetch"));
+    }
+
+    let refname = head
+        .name()
+        .ok_or_else(|| String::from("HEAD has no refname"))?;
+
+    let branch_upstream_remote_buf = repo
+        .branch_upstream_remote(refname)
+        .map_err(|e| format!("no upstream remote for {}: {}", refname, e))?;
+
+    let branch_upstream_remote = branch_upstream_remote_buf
+        .as_str()
+        .ok_or_else(|| String::from("upstream remote name is not valid UTF-8"))?;
+
+    let mut remote = repo
+        .find_remote(branch_upstream_remote)
+        .map_err(|e| format!("finding remote {}: {}", branch_upstream_remote, e))?;
+
+    let branch_upstream_name_buf = repo
+        .branch_upstream_name(refname)
+        .map_err(|e| format!("no upstream branch for {}: {}", refname, e))?;
+
+    let branch_upstream_name = branch_upstream_name_buf
+        .as_str()
+        .and_then(|str| str.split('/').last())
+        .ok_or_else(|| String::from("upstream branch name is not valid UTF-8"))?;
+
+    let mut callbacks = RemoteCallbacks::new();
+
+    // Try ssh-agent for SSH remotes, then the git credential helper for
+    // HTTPS remotes, then libgit2's default. Each kind is attempted at
+    // most once so a rejected credential can't loop forever.
+    let mut tried_ssh_key = false;
+    let mut tried_user_pass = false;
+    let mut tried_default = false;
+
+    callbacks.credentials(move |url, username_from_url, allowed_types| {
+        if allowed_types.contains(CredentialType::SSH_KEY) && !tried_ssh_key {
+            tried_ssh_key = true;
+
+            if let Some(username) = username_from_url {
+                if let Ok(cred) = Cred::ssh_key_from_agent(username) {
+                    return Ok(cred);
+                }
+            }
+        }
+
+        if allowed_types.contains(CredentialType::USER_PASS_PLAINTEXT) && !tried_user_pass {
+            tried_user_pass = true;
+
+            if let Some(cred) = credential_helper_fill(url, username_from_url) {
+                return Ok(cred);
+            }
+        }
+
+        if !tried_default {
+            tried_default = true;
+
+            return Cred::default();
+        }
+
+        Err(git2::Error::from_str(
+            "no remaining credential options for this remote",
+        ))
+    });
+
+    let mut options = FetchOptions::new();
+
+    options.remote_callbacks(callbacks);
+
+    remote
+        .fetch(&[branch_upstream_name], Some(&mut options), None)
+        .map_err(|e| format!("fetch from {} failed: {}", branch_upstream_remote, e))
+}
+
+/// Looks up HTTPS credentials via `git credential fill`, the same
+/// mechanism `git` itself uses to reach credential helpers (keychain,
+/// manager, cache, etc). Returns `None` on any failure so the caller can
+/// fall through to the next credential type.
+fn credential_helper_fill(url: &str, username_from_url: Option<&str>) -> Option<Cred> {
+    let mut request = format!("url={}\n", url);
+
+    if let Some(username) = username_from_url {
+        request.push_str(&format!("username={}\n", username));
+    }
+
+    request.push('\n');
+
+    let mut child = Command::new("git")
+        .args(["credential", "fill"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    child.stdin.take()?.write_all(request.as_bytes()).ok()?;
+
+    let output = child.wait_with_output().ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let response = String::from_utf8(output.stdout).ok()?;
+
+    let mut username = None;
+    let mut password = None;
+
+    for line in response.lines() {
+        if let Some(value) = line.strip_prefix("username=") {
+            username = Some(value);
+        } else if let Some(value) = line.strip_prefix("password=") {
+            password = Some(value);
+        }
+    }
+
+    Cred::userpass_plaintext(username?, password?).ok()
+}
+
@@ -0,0 +1,95 @@
+//! Opt-in diagnostics for the otherwise-silent background fetch path. The
+//! prompt itself never changes based on this module; it only exists so a
+//! user chasing down a fetch that never seems to happen has somewhere to
+//! look.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const ENABLE_VAR: &str = "PRETTY_GIT_STATUS_FETCH_LOG";
+
+/// Env var the prompt process sets on the detached background-fetch child
+/// so the child logs to the same file across prompt invocations. See
+/// [`shell_session_id`].
+pub const SESSION_ID_VAR: &str = "PRETTY_GIT_STATUS_SESSION_ID";
+
+/// Appends a timestamped diagnostic line to the session log file, but only
+/// when `PRETTY_GIT_STATUS_FETCH_LOG` is set, and only when `message`
+/// differs from the last line logged (so a remote that fails the same way
+/// on every prompt doesn't flood the file).
+pub fn log_fetch_event(message: &str) {
+    if env::var_os(ENABLE_VAR).is_none() {
+        return;
+    }
+
+    let path = match session_log_path() {
+        Some(path) => path,
+        None => return,
+    };
+
+    if last_logged_message(&path).as_deref() == Some(message) {
+        return;
+    }
+
+    let line = format!("[{}] {}\n", epoch_seconds(), message);
+
+    if let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open(&path) {
+        let _ = file.write_all(line.as_bytes());
+    }
+}
+
+fn session_log_path() -> Option<PathBuf> {
+    Some(env::temp_dir().join(format!("pretty-git-status-fetch-{}.log", session_id())))
+}
+
+/// The background-fetch child's own parent is the ephemeral prompt
+/// process (or whatever reaps it once that process has already exited),
+/// never the shell, so it can't derive a stable id by calling `getppid`
+/// itself. The prompt process calls `shell_session_id` *before* spawning
+/// the child and passes the result down via `SESSION_ID_VAR`; the child
+/// reads that instead. Falling back to our own pid keeps this usable for
+/// a standalone `--background-fetch` invocation with no such env var.
+fn session_id() -> u32 {
+    env::var(SESSION_ID_VAR)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(std::process::id)
+}
+
+/// A stable id for the current shell session: the current process's own
+/// parent pid. Called from the still-alive prompt process, whose parent
+/// is the shell itself, so this stays the same across every prompt
+/// render for as long as that shell is open.
+#[cfg(unix)]
+pub fn shell_session_id() -> u32 {
+    unsafe { libc_getppid() as u32 }
+}
+
+#[cfg(not(unix))]
+pub fn shell_session_id() -> u32 {
+    std::process::id()
+}
+
+#[cfg(unix)]
+extern "C" {
+    #[link_name = "getppid"]
+    fn libc_getppid() -> i32;
+}
+
+fn last_logged_message(path: &PathBuf) -> Option<String> {
+    let contents = fs::read_to_string(path).ok()?;
+    let last_line = contents.lines().last()?;
+    let (_, message) = last_line.split_once("] ")?;
+
+    Some(message.to_owned())
+}
+
+fn epoch_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -1,24 +1,29 @@
+mod cache;
+mod config;
+mod fetch;
+mod log;
+mod status;
+
 use colored_truecolor::Colorize;
-use git2::{
-    Branch, Cred, ErrorCode, FetchOptions, RemoteCallbacks, Repository, RepositoryState, Status,
-    Statuses,
-};
+use config::Config;
+use git2::{ErrorCode, Repository, RepositoryState};
 use std::fs;
 use std::io::{stdout, Write};
-use std::time::Duration;
 
 fn main() {
+    if env_args_request_background_fetch() {
+        fetch::run_background_fetch();
+        return;
+    }
+
+    let config = Config::load();
+
     let repo = match Repository::open_from_env() {
         Ok(repo) => repo,
         _ => return,
     };
 
-    try_fetch_current_branch(&repo);
-
-    let statuses = match repo.statuses(None) {
-        Ok(statuses) => statuses,
-        _ => return,
-    };
+    fetch::spawn_background_fetch_if_due(&repo);
 
     let head_name = get_head_name(&repo).unwrap_or(String::from("<unknown>"));
     let repo_state = match repo.state() {
@@ -32,134 +37,181 @@ fn main() {
         _ => head_name,
     };
 
-    let (is_local_only_branch, ahead, behind) = get_head_info(&repo);
-
-    let total_untracked = count_by_status(&statuses, Status::WT_NEW);
-
-    let total_changed = count_by_status(
-        &statuses,
-        Status::WT_DELETED | Status::WT_MODIFIED | Status::WT_RENAMED | Status::WT_TYPECHANGE,
+    let backend = status::Backend::from_config_str(&config.status_backend);
+    let counts = cache::compute_cached(&repo, backend);
+
+    let is_local_only_branch = counts.is_local_only_branch;
+    let ahead = counts.ahead;
+    let behind = counts.behind;
+    let total_untracked = counts.total_untracked;
+    let total_changed = counts.total_changed;
+    let total_staged = counts.total_staged;
+    let total_conflicted = counts.total_conflicted;
+    let total_stashed = counts.total_stashed;
+
+    let branch_segment = format!(
+        " on {}",
+        paint(head_label.bold().to_string(), &config.colors.branch)
     );
 
-    let total_staged = count_by_status(
-        &statuses,
-        Status::INDEX_MODIFIED
-            | Status::INDEX_NEW
-            | Status::INDEX_RENAMED
-            | Status::INDEX_TYPECHANGE,
-    );
-
-    let total_conflicted = count_by_status(&statuses, Status::CONFLICTED);
-
-    let total_stashed = count_stash();
-
-    let mut git_status = String::new();
-
-    git_status.push_str(format!(" on {}", head_label.bold().magenta()).as_str());
+    let mut sync_segment = String::new();
 
     if is_local_only_branch {
-        git_status.push_str(" ⬨")
-    } else if ahead > 0 || behind > 0 {
-        git_status.push(' ');
-
-        if ahead > 0 {
-            git_status.push_str(format!("↑{}", ahead).as_str());
+        if config.show_local_only_marker {
+            sync_segment.push(' ');
+            sync_segment.push_str(&config.symbols.local_only);
         }
-
-        if behind > 0 {
-            git_status.push_str(format!("↓{}", behind).as_str());
+    } else if config.show_sync_count && (ahead > 0 || behind > 0) {
+        sync_segment.push(' ');
+
+        if ahead > 0 && behind > 0 {
+            sync_segment.push_str(&config.symbols.diverged);
+
+            if config.show_diverged_counts {
+                sync_segment.push_str(&format!("{}/{}", ahead, behind));
+            }
+        } else if ahead > 0 {
+            sync_segment.push_str(&format!("{}{}", config.symbols.ahead, ahead));
+        } else if behind > 0 {
+            sync_segment.push_str(&format!("{}{}", config.symbols.behind, behind));
         }
     }
 
+    let mut counts_segment = String::new();
+
     if total_untracked > 0 || total_changed > 0 || total_staged > 0 || total_conflicted > 0 {
-        git_status.push_str(" (");
+        counts_segment.push_str(" (");
 
         if total_untracked > 0 {
-            git_status.push_str(format!("+{}", total_untracked).cyan().to_string().as_str());
+            counts_segment.push_str(&paint(
+                format!("{}{}", config.symbols.untracked, total_untracked),
+                &config.colors.untracked,
+            ));
         }
 
         if total_changed > 0 {
-            git_status.push_str(
-                format!("Δ{}", total_changed)
-                    .bright_magenta()
-                    .to_string()
-                    .as_str(),
-            );
+            counts_segment.push_str(&paint(
+                format!("{}{}", config.symbols.changed, total_changed),
+                &config.colors.changed,
+            ));
         }
 
         if total_staged > 0 {
-            git_status.push_str(format!("●{}", total_staged).red().to_string().as_str());
+            counts_segment.push_str(&paint(
+                format!("{}{}", config.symbols.staged, total_staged),
+                &config.colors.staged,
+            ));
         }
 
         if total_conflicted > 0 {
-            git_status.push_str(
-                format!("✖{}", total_conflicted)
-                    .yellow()
-                    .to_string()
-                    .as_str(),
-            );
+            counts_segment.push_str(&paint(
+                format!("{}{}", config.symbols.conflicted, total_conflicted),
+                &config.colors.conflicted,
+            ));
         }
 
-        git_status.push(')');
+        counts_segment.push(')');
     }
 
+    let mut stash_segment = String::new();
+
     if total_stashed > 0 {
-        git_status.push_str(format!(" ⚑{}", total_stashed).as_str());
+        stash_segment.push_str(&format!(" {}{}", config.symbols.stash, total_stashed));
     }
 
+    let rendered = config
+        .format
+        .replace("$branch", &branch_segment)
+        .replace("$sync", &sync_segment)
+        .replace("$counts", &counts_segment)
+        .replace("$stash", &stash_segment);
+
+    let git_status = format!("{}{}{}", config.prefix, rendered, config.suffix);
+
     stdout().write(git_status.as_bytes()).unwrap();
 }
 
-fn try_fetch_current_branch(repo: &Repository) -> Option<()> {
-    let head = repo.head().ok()?;
+/// Applies a configured color (a `#rrggbb` truecolor hex, or one of the
+/// named ANSI colors) to `text`. Unrecognized specs are returned unpainted
+/// rather than failing, since a bad config value shouldn't break the prompt.
+fn paint(text: String, spec: &str) -> String {
+    if let Some((r, g, b)) = parse_hex_color(spec) {
+        return text.true_color(r, g, b).to_string();
+    }
+
+    // `Color::from_str` only accepts space-separated names (`"bright
+    // magenta"`), so also accept the underscore/no-space spellings users
+    // are likely to reach for in a config file.
+    let normalized = spec.to_lowercase().replace('_', " ");
+
+    match normalized.parse::<colored_truecolor::Color>() {
+        Ok(color) => text.color(color).to_string(),
+        Err(_) => match normalized
+            .replacen("bright", "bright ", 1)
+            .parse::<colored_truecolor::Color>()
+        {
+            Ok(color) => text.color(color).to_string(),
+            Err(_) => text,
+        },
+    }
+}
+
+fn parse_hex_color(spec: &str) -> Option<(u8, u8, u8)> {
+    let hex = spec.strip_prefix('#')?;
 
-    // If we're not on a branch, don't bother
-    if !head.is_branch() {
+    if hex.len() != 6 {
         return None;
     }
 
-    let mut fetch_head_path = repo.path().to_owned();
+    let r = u8::from_str_radix(&hex[0..2], 16).ok()?;
+    let g = u8::from_str_radix(&hex[2..4], 16).ok()?;
+    let b = u8::from_str_radix(&hex[4..6], 16).ok()?;
 
-    fetch_head_path.push("FETCH_HEAD");
+    Some((r, g, b))
+}
 
-    // If we already fetched in the last 15 minutes, don't bother
-    if let Ok(metadata) = fs::metadata(fetch_head_path) {
-        let elapsed = metadata.modified().ok()?.elapsed().ok()?;
-        let fifteen_minutes = Duration::from_secs(60 * 15);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        if elapsed < fifteen_minutes {
-            return None;
-        }
+    #[test]
+    fn parse_hex_color_lowercase() {
+        assert_eq!(parse_hex_color("#ff00aa"), Some((0xff, 0x00, 0xaa)));
     }
 
-    let refname = head.name()?;
-    let branch_upstream_remote_buf = repo.branch_upstream_remote(refname).ok()?;
-    let branch_upstream_remote = branch_upstream_remote_buf.as_str()?;
-
-    let mut remote = repo.find_remote(branch_upstream_remote).ok()?;
+    #[test]
+    fn parse_hex_color_uppercase() {
+        assert_eq!(parse_hex_color("#FF00AA"), Some((0xff, 0x00, 0xaa)));
+    }
 
-    let branch_upstream_name_buf = repo.branch_upstream_name(refname).ok()?;
-    let branch_upstream_name = branch_upstream_name_buf
-        .as_str()
-        .map(|str| str.split('/').last().to_owned())??;
+    #[test]
+    fn parse_hex_color_rejects_missing_hash() {
+        assert_eq!(parse_hex_color("ff00aa"), None);
+    }
 
-    let mut callbacks = RemoteCallbacks::new();
+    #[test]
+    fn parse_hex_color_rejects_wrong_length() {
+        assert_eq!(parse_hex_color("#fff"), None);
+    }
 
-    // Look for credentials on the ssh-agent
-    callbacks.credentials(
-        |_url, username_from_url, _allowed_types| match username_from_url {
-            Some(username) => Cred::ssh_key_from_agent(username),
-            None => Cred::default(),
-        },
-    );
+    #[test]
+    fn parse_hex_color_rejects_non_hex_digits() {
+        assert_eq!(parse_hex_color("#zzzzzz"), None);
+    }
 
-    let mut options = FetchOptions::new();
+    #[test]
+    fn paint_hex_spec_uses_truecolor() {
+        let painted = paint(String::from("x"), "#ff00aa");
 
-    options.remote_callbacks(callbacks);
+        assert_eq!(painted, "x".true_color(0xff, 0x00, 0xaa).to_string());
+        assert_ne!(painted, "x");
+    }
+}
 
-    remote
-        .fetch(&[branch_upstream_name], Some(&mut options), None)
-        .ok()
+/// Checks argv for the flag that re-invokes this binary as the detached
+/// background-fetch helper (see `fetch::spawn_background_fetch_if_due`).
+fn env_args_request_background_fetch() -> bool {
+    std::env::args().nth(1).as_deref() == Some(fetch::BACKGROUND_FETCH_FLAG)
 }
 
 fn get_head_name(repo: &Repository) -> Option<String> {
@@ -197,67 +249,3 @@ fn get_head_name(repo: &Repository) -> Option<String> {
 
     return Some(format!(":{}", sha));
 }
-
-fn get_head_info(repo: &Repository) -> (bool, usize, usize) {
-    let head = match repo.head() {
-        Ok(head) => head,
-        _ => return (false, 0, 0),
-    };
-
-    if !head.is_branch() {
-        return (false, 0, 0);
-    }
-
-    let branch = Branch::wrap(head);
-
-    let upstream = match branch.upstream() {
-        Ok(upstream) => upstream,
-        _ => return (true, 0, 0),
-    };
-
-    let branch_oid = match branch.get().target() {
-        Some(branch_oid) => branch_oid,
-        _ => return (false, 0, 0),
-    };
-
-    let upstream_oid = match upstream.get().target() {
-        Some(upstream_oid) => upstream_oid,
-        _ => return (false, 0, 0),
-    };
-
-    let (ahead, behind) = repo
-        .graph_ahead_behind(branch_oid, upstream_oid)
-        .unwrap_or((0, 0));
-
-    return (false, ahead, behind);
-}
-
-fn count_by_status(statuses: &Statuses, status: Status) -> i32 {
-    let mut counter = 0;
-
-    for entry in statuses.iter() {
-        if entry.status().intersects(status) {
-            counter += 1;
-        }
-    }
-
-    return counter;
-}
-
-fn count_stash() -> i32 {
-    let mut repo = match Repository::open_from_env() {
-        Ok(repo) => repo,
-        _ => return 0,
-    };
-
-    let mut counter = 0;
-
-    repo.stash_foreach(|_one, _two, _three| {
-        counter += 1;
-
-        return true;
-    })
-    .ok();
-
-    return counter;
-}
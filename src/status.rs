@@ -0,0 +1,379 @@
+use git2::Repository;
+use std::env;
+
+/// Which implementation computes the working-tree/ahead-behind counts.
+/// `Git` shells out to the `git` executable, which scans far less of the
+/// working tree per invocation than libgit2's full `statuses()` walk on
+/// large repositories. `Auto` picks `Git` when it's on `PATH`, else falls
+/// back to `Libgit2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Auto,
+    Libgit2,
+    Git,
+}
+
+impl Backend {
+    pub fn from_config_str(value: &str) -> Backend {
+        match value {
+            "git" => Backend::Git,
+            "libgit2" => Backend::Libgit2,
+            _ => Backend::Auto,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Counts {
+    pub total_untracked: i32,
+    pub total_changed: i32,
+    pub total_staged: i32,
+    pub total_conflicted: i32,
+    pub total_stashed: i32,
+    pub is_local_only_branch: bool,
+    pub ahead: usize,
+    pub behind: usize,
+}
+
+pub fn compute(repo: &Repository, backend: Backend) -> Counts {
+    let use_git = match backend {
+        Backend::Git => true,
+        Backend::Libgit2 => false,
+        Backend::Auto => git_on_path(),
+    };
+
+    if use_git {
+        if let Some(counts) = git_backend::compute(repo) {
+            return counts;
+        }
+    }
+
+    libgit2_backend::compute(repo)
+}
+
+fn git_on_path() -> bool {
+    env::var_os("PATH")
+        .map(|path| {
+            env::split_paths(&path).any(|dir| {
+                dir.join(if cfg!(windows) { "git.exe" } else { "git" })
+                    .is_file()
+            })
+        })
+        .unwrap_or(false)
+}
+
+mod libgit2_backend {
+    use super::Counts;
+    use git2::{Branch, Repository, Status};
+
+    pub fn compute(repo: &Repository) -> Counts {
+        let mut counts = Counts::default();
+
+        if let Ok(statuses) = repo.statuses(None) {
+            counts.total_untracked = count_by_status(&statuses, Status::WT_NEW);
+
+            counts.total_changed = count_by_status(
+                &statuses,
+                Status::WT_DELETED
+                    | Status::WT_MODIFIED
+                    | Status::WT_RENAMED
+                    | Status::WT_TYPECHANGE,
+            );
+
+            counts.total_staged = count_by_status(
+                &statuses,
+                Status::INDEX_MODIFIED
+                    | Status::INDEX_NEW
+                    | Status::INDEX_RENAMED
+                    | Status::INDEX_TYPECHANGE,
+            );
+
+            counts.total_conflicted = count_by_status(&statuses, Status::CONFLICTED);
+        }
+
+        let (is_local_only_branch, ahead, behind) = head_info(repo);
+
+        counts.is_local_only_branch = is_local_only_branch;
+        counts.ahead = ahead;
+        counts.behind = behind;
+        counts.total_stashed = count_stash(repo);
+
+        counts
+    }
+
+    fn count_by_status(statuses: &git2::Statuses, status: Status) -> i32 {
+        let mut counter = 0;
+
+        for entry in statuses.iter() {
+            if entry.status().intersects(status) {
+                counter += 1;
+            }
+        }
+
+        counter
+    }
+
+    fn head_info(repo: &Repository) -> (bool, usize, usize) {
+        let head = match repo.head() {
+            Ok(head) => head,
+            _ => return (false, 0, 0),
+        };
+
+        if !head.is_branch() {
+            return (false, 0, 0);
+        }
+
+        let branch = Branch::wrap(head);
+
+        let upstream = match branch.upstream() {
+            Ok(upstream) => upstream,
+            _ => return (true, 0, 0),
+        };
+
+        let branch_oid = match branch.get().target() {
+            Some(branch_oid) => branch_oid,
+            _ => return (false, 0, 0),
+        };
+
+        let upstream_oid = match upstream.get().target() {
+            Some(upstream_oid) => upstream_oid,
+            _ => return (false, 0, 0),
+        };
+
+        let (ahead, behind) = repo
+            .graph_ahead_behind(branch_oid, upstream_oid)
+            .unwrap_or((0, 0));
+
+        (false, ahead, behind)
+    }
+
+    fn count_stash(repo: &Repository) -> i32 {
+        // `stash_foreach` needs a mutable borrow that the rest of this
+        // module only has shared access to, so open a second handle.
+        let mut repo = match Repository::open(repo.path()) {
+            Ok(repo) => repo,
+            _ => return 0,
+        };
+
+        let mut counter = 0;
+
+        repo.stash_foreach(|_one, _two, _three| {
+            counter += 1;
+
+            true
+        })
+        .ok();
+
+        counter
+    }
+}
+
+mod git_backend {
+    use super::Counts;
+    use git2::Repository;
+    use std::process::Command;
+
+    pub fn compute(repo: &Repository) -> Option<Counts> {
+        let workdir = repo.workdir()?;
+
+        let status_output = Command::new("git")
+            .arg("-C")
+            .arg(workdir)
+            .args(["status", "--porcelain=v2", "--branch"])
+            .output()
+            .ok()?;
+
+        if !status_output.status.success() {
+            return None;
+        }
+
+        let stash_output = Command::new("git")
+            .arg("-C")
+            .arg(workdir)
+            .args(["stash", "list"])
+            .output()
+            .ok()?;
+
+        let stdout = String::from_utf8(status_output.stdout).ok()?;
+
+        let mut counts = parse_porcelain_v2(&stdout);
+
+        if let Ok(stdout) = String::from_utf8(stash_output.stdout) {
+            counts.total_stashed = stdout.lines().count() as i32;
+        }
+
+        Some(counts)
+    }
+
+    /// Parses the body of `git status --porcelain=v2 --branch` into
+    /// counts. Pulled out of `compute` so it can be exercised without a
+    /// real repository or the `git` executable.
+    fn parse_porcelain_v2(stdout: &str) -> Counts {
+        let mut counts = Counts::default();
+        let mut saw_upstream_header = false;
+        let mut detached = false;
+
+        for line in stdout.lines() {
+            if let Some(rest) = line.strip_prefix("# branch.head ") {
+                detached = rest == "(detached)";
+            } else if line.starts_with("# branch.upstream ") {
+                saw_upstream_header = true;
+            } else if let Some(rest) = line.strip_prefix("# branch.ab ") {
+                let (ahead, behind) = parse_ahead_behind(rest).unwrap_or((0, 0));
+                counts.ahead = ahead;
+                counts.behind = behind;
+            } else if let Some(xy) = line.strip_prefix("1 ").or_else(|| line.strip_prefix("2 ")) {
+                classify_xy(xy, &mut counts);
+            } else if line.starts_with("u ") {
+                counts.total_conflicted += 1;
+            } else if line.starts_with("? ") {
+                counts.total_untracked += 1;
+            }
+        }
+
+        counts.is_local_only_branch = !detached && !saw_upstream_header;
+
+        counts
+    }
+
+    /// `xy` starts with the two status characters, e.g. `M.` or `.M` or `R.`.
+    ///
+    /// Staged deletions (`D.`) are deliberately excluded from
+    /// `total_staged` to match `libgit2_backend`, whose mask omits
+    /// `Status::INDEX_DELETED`.
+    fn classify_xy(xy: &str, counts: &mut Counts) {
+        let mut chars = xy.chars();
+        let staged = chars.next().unwrap_or('.');
+        let unstaged = chars.next().unwrap_or('.');
+
+        if staged != '.' && staged != 'D' {
+            counts.total_staged += 1;
+        }
+
+        if unstaged != '.' {
+            counts.total_changed += 1;
+        }
+    }
+
+    /// Parses `+N -M` from a `branch.ab` header value.
+    fn parse_ahead_behind(value: &str) -> Option<(usize, usize)> {
+        let mut ahead = None;
+        let mut behind = None;
+
+        for token in value.split_whitespace() {
+            if let Some(n) = token.strip_prefix('+') {
+                ahead = n.parse().ok();
+            } else if let Some(n) = token.strip_prefix('-') {
+                behind = n.parse().ok();
+            }
+        }
+
+        Some((ahead?, behind?))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn classify_xy_ordinary_staged_entry() {
+            let mut counts = Counts::default();
+            classify_xy("M.", &mut counts);
+
+            assert_eq!(counts.total_staged, 1);
+            assert_eq!(counts.total_changed, 0);
+        }
+
+        #[test]
+        fn classify_xy_ordinary_unstaged_entry() {
+            let mut counts = Counts::default();
+            classify_xy(".M", &mut counts);
+
+            assert_eq!(counts.total_staged, 0);
+            assert_eq!(counts.total_changed, 1);
+        }
+
+        #[test]
+        fn classify_xy_staged_and_unstaged_in_one_entry() {
+            let mut counts = Counts::default();
+            classify_xy("MM", &mut counts);
+
+            assert_eq!(counts.total_staged, 1);
+            assert_eq!(counts.total_changed, 1);
+        }
+
+        #[test]
+        fn classify_xy_staged_rename() {
+            let mut counts = Counts::default();
+            classify_xy("R.", &mut counts);
+
+            assert_eq!(counts.total_staged, 1);
+            assert_eq!(counts.total_changed, 0);
+        }
+
+        #[test]
+        fn classify_xy_excludes_staged_deletion() {
+            let mut counts = Counts::default();
+            classify_xy("D.", &mut counts);
+
+            assert_eq!(counts.total_staged, 0);
+            assert_eq!(counts.total_changed, 0);
+        }
+
+        #[test]
+        fn parse_ahead_behind_both_nonzero() {
+            assert_eq!(parse_ahead_behind("+3 -2"), Some((3, 2)));
+        }
+
+        #[test]
+        fn parse_ahead_behind_order_independent() {
+            assert_eq!(parse_ahead_behind("-2 +3"), Some((3, 2)));
+        }
+
+        #[test]
+        fn parse_ahead_behind_rejects_malformed_input() {
+            assert_eq!(parse_ahead_behind("not ahead/behind"), None);
+        }
+
+        #[test]
+        fn parse_porcelain_v2_ordinary_and_renamed_entries() {
+            let stdout = "\
+# branch.oid deadbeef
+# branch.head main
+# branch.upstream origin/main
+# branch.ab +1 -0
+1 M. N... 100644 100644 100644 abcd1234 abcd1234 staged.txt
+1 .M N... 100644 100644 100644 abcd1234 abcd1234 unstaged.txt
+1 MM N... 100644 100644 100644 abcd1234 abcd1234 both.txt
+2 R. N... 100644 100644 100644 abcd1234 abcd1234 R100 new.txt\told.txt
+u UU N... 100644 100644 100644 100644 abcd1234 abcd1234 abcd1234 conflict.txt
+? untracked.txt
+";
+
+            let counts = parse_porcelain_v2(stdout);
+
+            assert_eq!(counts.ahead, 1);
+            assert_eq!(counts.behind, 0);
+            assert_eq!(counts.total_staged, 3); // staged.txt, both.txt, new.txt
+            assert_eq!(counts.total_changed, 2); // unstaged.txt, both.txt
+            assert_eq!(counts.total_conflicted, 1);
+            assert_eq!(counts.total_untracked, 1);
+            assert!(!counts.is_local_only_branch);
+        }
+
+        #[test]
+        fn parse_porcelain_v2_no_upstream_is_local_only() {
+            let stdout = "\
+# branch.oid deadbeef
+# branch.head main
+1 M. N... 100644 100644 100644 abcd1234 abcd1234 staged.txt
+";
+
+            let counts = parse_porcelain_v2(stdout);
+
+            assert_eq!(counts.ahead, 0);
+            assert_eq!(counts.behind, 0);
+            assert!(counts.is_local_only_branch);
+        }
+    }
+}